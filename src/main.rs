@@ -1,3 +1,8 @@
+#[cfg(unix)]
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
 use std::{
     io::{self, Read, Write},
     sync::mpsc,
@@ -5,7 +10,7 @@ use std::{
     time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use crossterm::{
     execute,
     style::ResetColor,
@@ -13,7 +18,33 @@ use crossterm::{
 };
 use portable_pty::{CommandBuilder, NativePtySystem, PtySize, PtySystem};
 
+/// 默认不启用 detach 按键序列，需通过 `INTERACTIVE_SHELL_DETACH` 显式开启 (如 `ctrl-a d`)。
+/// detach 会关闭 shell，且前缀键会被拦截到下一次按键 (Ctrl-A 在 bash/zsh/PSReadLine 中是行首)，
+/// 因此不适合默认占用
+const DEFAULT_DETACH_KEYS: &str = "none";
+
+/// 输入/输出线程通知主线程退出的原因
+#[derive(PartialEq)]
+enum ExitReason {
+    Eof,
+    Detach,
+}
+
 fn main() -> Result<()> {
+    // 在进入 Raw Mode 之前解析配置，出错时错误信息可以正常显示
+    let detach_spec =
+        std::env::var("INTERACTIVE_SHELL_DETACH").unwrap_or(DEFAULT_DETACH_KEYS.into());
+    let detach_keys = DetachSequence::parse(&detach_spec)?;
+
+    // Unix 上先注册 SIGWINCH (同样可能失败，放在 Raw Mode 之前)，
+    // 避免错过读取尺寸之后发生的窗口变化
+    #[cfg(unix)]
+    let resized = {
+        let flag = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::consts::SIGWINCH, Arc::clone(&flag))?;
+        flag
+    };
+
     // 0. 提前开启 Raw Mode 并初始化终端，确保 VT 序列能被正确处理
     enable_raw_mode()?;
     execute!(io::stdout(), ResetColor)?;
@@ -32,12 +63,15 @@ fn main() -> Result<()> {
     })?;
 
     // 3. 启动 Shell
-    // 在 Windows 上我们一般用 cmd 或 powershell，在 Unix 上用 SHELL 环境变量或 bash
+    // INTERACTIVE_SHELL 环境变量在所有平台上优先；否则 Windows 上优先用 pwsh/powershell，
+    // 找不到时回退到 cmd，Unix 上用 SHELL 环境变量或 bash
     #[cfg(target_os = "windows")]
-    let cmd = CommandBuilder::new("cmd");
+    let cmd = windows_shell_command();
     #[cfg(not(target_os = "windows"))]
     let cmd = {
-        let shell = std::env::var("SHELL").unwrap_or("bash".into());
+        let shell = std::env::var("INTERACTIVE_SHELL")
+            .or_else(|_| std::env::var("SHELL"))
+            .unwrap_or("bash".into());
         CommandBuilder::new(shell)
     };
 
@@ -51,7 +85,11 @@ fn main() -> Result<()> {
     let mut reader = pair.master.try_clone_reader()?;
     let mut writer = pair.master.take_writer()?;
 
-    println!("Interactive shell started. Type 'exit' to quit.\r");
+    if detach_keys.is_some() {
+        println!("Interactive shell started. Type 'exit' or press '{detach_spec}' to quit.\r");
+    } else {
+        println!("Interactive shell started. Type 'exit' to quit.\r");
+    }
 
     // 使用 channel 来通知主线程退出
     let (tx, rx) = mpsc::channel();
@@ -74,29 +112,55 @@ fn main() -> Result<()> {
                 Err(_) => break,
             }
         }
-        let _ = tx_out.send(());
+        let _ = tx_out.send(ExitReason::Eof);
     });
 
     // 6. 输入线程: Host Stdin -> PTY Master
     // 注意：在 Raw Mode 下，input 也是逐字节读取的
     let tx_in = tx.clone();
     thread::spawn(move || {
+        let mut detach_keys = detach_keys;
         let mut stdin = io::stdin();
         let mut buf = [0u8; 1024];
+        let mut filtered = Vec::with_capacity(buf.len());
+        let mut reason = ExitReason::Eof;
         loop {
             match stdin.read(&mut buf) {
                 Ok(0) => break,
                 Ok(n) => {
-                    if writer.write_all(&buf[..n]).is_err() {
+                    // 拦截 detach 序列，其余字节原样转发给 shell
+                    let input = match detach_keys.as_mut() {
+                        Some(keys) => {
+                            filtered.clear();
+                            if keys.filter(&buf[..n], &mut filtered) {
+                                reason = ExitReason::Detach;
+                            }
+                            &filtered[..]
+                        }
+                        None => &buf[..n],
+                    };
+                    if writer.write_all(input).is_err() || reason == ExitReason::Detach {
                         break;
                     }
                 }
                 Err(_) => break,
             }
         }
-        let _ = tx_in.send(());
+        if reason == ExitReason::Detach {
+            // 不能释放 writer：portable-pty 在 Unix 上 drop writer 时会写入 "\n" 和 VEOF，
+            // 会把用户输入了一半的命令行当作回车执行。detach 之后不能再向 PTY 写任何内容
+            std::mem::forget(writer);
+        } else if let Some(prefix) = detach_keys.as_mut().and_then(DetachSequence::take_pending) {
+            // 输入在前缀键之后结束，把尚未转发的前缀键补发出去
+            let _ = writer.write_all(&[prefix]);
+        }
+        let _ = tx_in.send(reason);
     });
 
+    // 记录当前 PTY 尺寸，用于检测宿主终端窗口大小变化
+    let mut last_size = (cols, rows);
+    let mut detached = false;
+
     // 等待任意一方结束 (通常是 shell 退出导致 reader EOF)
     // On Windows, the reader thread looks like it hangs on exit because
     // the handle isn't signaled immediately or correctly.
@@ -108,18 +172,244 @@ fn main() -> Result<()> {
             Err(_) => break,
         }
 
-        if let Ok(_) = rx.try_recv() {
+        if let Ok(reason) = rx.try_recv() {
+            detached = reason == ExitReason::Detach;
             break;
         }
 
+        // 同步窗口大小：Unix 上只在收到 SIGWINCH 后读取尺寸；Windows 没有对应信号，
+        // 而 crossterm 的 resize 事件会与输入线程争抢 stdin，因此每个周期轮询一次。
+        // 尺寸变化后调用 resize，PTY 会向子进程发送 SIGWINCH，让 vim/htop 重新布局
+        #[cfg(unix)]
+        let check_size = resized.swap(false, Ordering::Relaxed);
+        #[cfg(not(unix))]
+        let check_size = true;
+
+        if check_size
+            && let Ok(size) = crossterm::terminal::size()
+            && size != last_size
+        {
+            let (cols, rows) = size;
+            let _ = pair.master.resize(PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            });
+            last_size = size;
+        }
+
         thread::sleep(Duration::from_millis(50));
     }
 
     // 7. 恢复终端模式
     disable_raw_mode()?;
 
+    // detach 只是不输入 exit 直接离开，不会保留 shell：这里不等待子进程，
+    // 返回后 PTY master 被释放，shell 随之被关闭 (Unix 上收到 SIGHUP)，之后也无法重新连接。
+    // 不等待是为了在 shell 卡住、不响应输入时也能离开
+    if detached {
+        println!("\n[detached, shell closed]");
+        return Ok(());
+    }
+
     // 等待子进程彻底退出
     let _ = child.wait();
 
     Ok(())
 }
+
+/// screen 风格的 detach 序列：先按前缀键，再按 detach 键
+struct DetachSequence {
+    prefix: u8,
+    key: u8,
+    /// 上一个字节是前缀键，尚未转发
+    pending: bool,
+}
+
+impl DetachSequence {
+    /// 解析形如 `ctrl-a d` 的配置，`none` 或空字符串表示关闭 detach
+    fn parse(spec: &str) -> Result<Option<Self>> {
+        let spec = spec.trim();
+        if spec.is_empty() || spec.eq_ignore_ascii_case("none") {
+            return Ok(None);
+        }
+        let mut tokens = spec.split_whitespace();
+        let (Some(prefix), Some(key), None) = (tokens.next(), tokens.next(), tokens.next()) else {
+            bail!("invalid detach keys {spec:?}: expected \"<prefix> <key>\", e.g. \"ctrl-a d\"");
+        };
+        Ok(Some(Self {
+            prefix: parse_key(prefix)?,
+            key: parse_key(key)?,
+            pending: false,
+        }))
+    }
+
+    /// 把 `input` 中需要转发的字节写入 `out`，遇到完整的 detach 序列时返回 `true`。
+    /// 前缀键后跟其他按键时，两个字节都会原样转发
+    fn filter(&mut self, input: &[u8], out: &mut Vec<u8>) -> bool {
+        for &byte in input {
+            if self.pending {
+                self.pending = false;
+                if byte == self.key {
+                    return true;
+                }
+                out.push(self.prefix);
+                out.push(byte);
+            } else if byte == self.prefix {
+                self.pending = true;
+            } else {
+                out.push(byte);
+            }
+        }
+        false
+    }
+
+    /// 取出尚未转发的前缀键，输入在前缀键之后结束时使用
+    fn take_pending(&mut self) -> Option<u8> {
+        std::mem::take(&mut self.pending).then_some(self.prefix)
+    }
+}
+
+/// 解析单个按键：`ctrl-<c>` 表示控制字符，否则必须是单个 ASCII 字符
+fn parse_key(token: &str) -> Result<u8> {
+    let lower = token.to_ascii_lowercase();
+    if let Some(c) = lower.strip_prefix("ctrl-") {
+        if let [c @ (b'a'..=b'z' | b'@' | b'[' | b'\\' | b']' | b'^' | b'_')] = c.as_bytes() {
+            return Ok(c.to_ascii_uppercase() & 0x1f);
+        }
+    } else if let [c] = token.as_bytes()
+        && c.is_ascii()
+    {
+        return Ok(*c);
+    }
+    bail!("invalid detach key {token:?}")
+}
+
+/// 选择 Windows 下的 shell：`INTERACTIVE_SHELL` 环境变量优先，
+/// 否则依次尝试 PATH 中的 `pwsh`、`powershell`，最后回退到 `cmd`。
+#[cfg(target_os = "windows")]
+fn windows_shell_command() -> CommandBuilder {
+    let shell = std::env::var("INTERACTIVE_SHELL").ok().unwrap_or_else(|| {
+        ["pwsh.exe", "powershell.exe"]
+            .into_iter()
+            .find(|name| find_in_path(name))
+            .unwrap_or("cmd.exe")
+            .into()
+    });
+
+    let mut cmd = CommandBuilder::new(&shell);
+    if is_powershell(&shell) {
+        // 覆盖 profile 中可能定义的花哨 prompt，保持输出干净、便于解析
+        cmd.args([
+            "-NoLogo",
+            "-NoExit",
+            "-Command",
+            "function prompt { \"PS $($PWD.Path)> \" }",
+        ]);
+    }
+    cmd
+}
+
+#[cfg(target_os = "windows")]
+fn is_powershell(shell: &str) -> bool {
+    let name = std::path::Path::new(shell)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    name == "pwsh" || name == "powershell"
+}
+
+#[cfg(target_os = "windows")]
+fn find_in_path(name: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(name).is_file()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctrl_a_d() -> DetachSequence {
+        DetachSequence::parse("ctrl-a d").unwrap().unwrap()
+    }
+
+    #[test]
+    fn detach_sequence_split_across_reads() {
+        let mut keys = ctrl_a_d();
+        let mut out = Vec::new();
+        assert!(!keys.filter(b"ls\x01", &mut out));
+        assert_eq!(out, b"ls");
+        out.clear();
+        assert!(keys.filter(b"d", &mut out));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn prefix_followed_by_other_key_forwards_both() {
+        let mut keys = ctrl_a_d();
+        let mut out = Vec::new();
+        assert!(!keys.filter(b"\x01x", &mut out));
+        assert_eq!(out, b"\x01x");
+        assert_eq!(keys.take_pending(), None);
+    }
+
+    #[test]
+    fn bytes_before_sequence_are_forwarded() {
+        let mut keys = ctrl_a_d();
+        let mut out = Vec::new();
+        assert!(keys.filter(b"echo hi\x01dafter", &mut out));
+        assert_eq!(out, b"echo hi");
+    }
+
+    #[test]
+    fn pending_prefix_is_taken_once() {
+        let mut keys = ctrl_a_d();
+        let mut out = Vec::new();
+        assert!(!keys.filter(b"\x01", &mut out));
+        assert!(out.is_empty());
+        assert_eq!(keys.take_pending(), Some(0x01));
+        assert_eq!(keys.take_pending(), None);
+    }
+
+    #[test]
+    fn prefix_equal_to_key_detaches_on_second_press() {
+        let mut keys = DetachSequence::parse("ctrl-a ctrl-a").unwrap().unwrap();
+        let mut out = Vec::new();
+        assert!(keys.filter(b"\x01\x01", &mut out));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn parse_keys() {
+        let keys = DetachSequence::parse("  CTRL-B D ").unwrap().unwrap();
+        assert_eq!((keys.prefix, keys.key), (0x02, b'D'));
+        let keys = DetachSequence::parse("ctrl-[ ctrl-\\").unwrap().unwrap();
+        assert_eq!((keys.prefix, keys.key), (0x1b, 0x1c));
+    }
+
+    #[test]
+    fn parse_disabled() {
+        assert!(DetachSequence::parse("").unwrap().is_none());
+        assert!(DetachSequence::parse("NONE").unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_rejects_malformed_specs() {
+        for spec in [
+            "ctrl-a",
+            "ctrl-a d x",
+            "ctrl-1 d",
+            "ctrl- d",
+            "ctrl-a dd",
+            "ctrl-a é",
+        ] {
+            assert!(
+                DetachSequence::parse(spec).is_err(),
+                "{spec:?} should be rejected"
+            );
+        }
+    }
+}